@@ -0,0 +1,135 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use lambda_http::Request;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+
+/// JWT configuration loaded once from env at cold start.
+pub struct Config {
+    secret: String,
+    expires_in_seconds: i64,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, AppError> {
+        let secret = env::var("JWT_SECRET").map_err(|_| AppError::MissingConfig("JWT_SECRET"))?;
+        let expires_in_seconds = env::var("JWT_MAXAGE")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(60 * 60);
+
+        Ok(Self { secret, expires_in_seconds })
+    }
+}
+
+/// Claims carried by a staff session token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: i32,
+    pub exp: usize,
+}
+
+pub fn issue_token(staff_id: i32, config: &Config) -> Result<String, AppError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is after the epoch")
+        .as_secs() as i64;
+
+    let claims = Claims {
+        sub: staff_id,
+        exp: (now + config.expires_in_seconds) as usize,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.secret.as_bytes()),
+    )
+    .map_err(|_| AppError::Unauthorized)
+}
+
+/// Extract and validate the bearer token on a request, returning its claims.
+pub fn authorize(event: &Request, config: &Config) -> Result<Claims, AppError> {
+    let token = bearer_token(event).ok_or(AppError::Unauthorized)?;
+
+    let mut validation = Validation::default();
+    validation.leeway = 0;
+
+    let data = decode::<Claims>(
+        &token,
+        &DecodingKey::from_secret(config.secret.as_bytes()),
+        &validation,
+    )
+    .map_err(|_| AppError::Unauthorized)?;
+
+    Ok(data.claims)
+}
+
+fn bearer_token(event: &Request) -> Option<String> {
+    event
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::Body;
+
+    fn config(expires_in_seconds: i64) -> Config {
+        Config {
+            secret: "test-secret".to_string(),
+            expires_in_seconds,
+        }
+    }
+
+    fn request_with_bearer(token: &str) -> Request {
+        Request::builder()
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::Empty)
+            .unwrap()
+    }
+
+    #[test]
+    fn issues_and_authorizes_a_valid_token() {
+        let config = config(60);
+        let token = issue_token(42, &config).unwrap();
+
+        let claims = authorize(&request_with_bearer(&token), &config).unwrap();
+
+        assert_eq!(claims.sub, 42);
+    }
+
+    #[test]
+    fn rejects_a_token_signed_with_a_different_secret() {
+        let config = config(60);
+        let other_config = Config {
+            secret: "other-secret".to_string(),
+            expires_in_seconds: 60,
+        };
+        let token = issue_token(42, &config).unwrap();
+
+        assert!(authorize(&request_with_bearer(&token), &other_config).is_err());
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let config = config(-1);
+        let token = issue_token(42, &config).unwrap();
+
+        assert!(authorize(&request_with_bearer(&token), &config).is_err());
+    }
+
+    #[test]
+    fn rejects_a_request_with_no_authorization_header() {
+        let config = config(60);
+        let request = Request::builder().body(Body::Empty).unwrap();
+
+        assert!(authorize(&request, &config).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use subtle::ConstantTimeEq;
+
+pub const COOKIE_NAME: &str = "csrf_token";
+
+/// Generate a random token for the double-submit-cookie CSRF scheme.
+pub fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Compare the token submitted in the form body against the one set in the
+/// `csrf_token` cookie, in constant time.
+pub fn tokens_match(form_token: &str, cookie_token: &str) -> bool {
+    form_token.as_bytes().ct_eq(cookie_token.as_bytes()).into()
+}
+
+/// Pull the `csrf_token` cookie value out of a raw `Cookie` header.
+pub fn cookie_token(cookie_header: &str) -> Option<&str> {
+    cookie_header.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        (name == COOKIE_NAME).then_some(value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_tokens_compare_equal() {
+        assert!(tokens_match("abc123", "abc123"));
+    }
+
+    #[test]
+    fn mismatched_tokens_compare_unequal() {
+        assert!(!tokens_match("abc123", "xyz789"));
+    }
+
+    #[test]
+    fn tokens_of_different_length_compare_unequal() {
+        assert!(!tokens_match("short", "much-longer-token"));
+    }
+
+    #[test]
+    fn generated_tokens_are_not_reused() {
+        assert_ne!(generate_token(), generate_token());
+    }
+
+    #[test]
+    fn finds_the_csrf_cookie_among_others() {
+        let header = "other=1; csrf_token=abc123; another=2";
+        assert_eq!(cookie_token(header), Some("abc123"));
+    }
+
+    #[test]
+    fn returns_none_when_the_cookie_is_absent() {
+        assert_eq!(cookie_token("other=1; another=2"), None);
+    }
+}
@@ -0,0 +1,78 @@
+use lambda_http::Response;
+
+/// Domain error type for the staff handler.
+///
+/// Each variant knows how to render itself as an HTTP response, so handlers
+/// can simply bubble errors up with `?` instead of panicking.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("not found")]
+    NotFound,
+
+    #[error("bad request: {0}")]
+    BadRequest(String),
+
+    #[error("unauthorized")]
+    Unauthorized,
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("database error: {0}")]
+    Database(#[from] mysql::Error),
+
+    #[error("template error: {0}")]
+    Template(#[from] handlebars::RenderError),
+
+    #[error("missing configuration: {0}")]
+    MissingConfig(&'static str),
+
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> u16 {
+        match self {
+            AppError::NotFound => 404,
+            AppError::BadRequest(_) => 400,
+            AppError::Unauthorized => 401,
+            AppError::Forbidden(_) => 403,
+            AppError::Database(_)
+            | AppError::Template(_)
+            | AppError::MissingConfig(_)
+            | AppError::Internal(_) => 500,
+        }
+    }
+}
+
+impl AppError {
+    /// Message safe to return to the caller. The 500-class variants wrap
+    /// internal DB/template/config detail that belongs in the log line
+    /// (`function_handler` logs `self` via `tracing` before calling this),
+    /// not in a response body.
+    fn public_message(&self) -> String {
+        match self {
+            AppError::Database(_)
+            | AppError::Template(_)
+            | AppError::MissingConfig(_)
+            | AppError::Internal(_) => "internal server error".to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn into_response(self) -> Response<String> {
+        let status = self.status();
+        let body = format!(
+            "<html><body><h1>{}</h1><p>{}</p></body></html>",
+            status,
+            self.public_message()
+        );
+
+        Response::builder()
+            .status(status)
+            .header("content-type", "text/html")
+            .body(body)
+            .expect("status and headers are always valid")
+    }
+}
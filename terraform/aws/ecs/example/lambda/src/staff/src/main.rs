@@ -1,15 +1,41 @@
-use tracing::{event, Level};
+use tracing::{event, Instrument, Level};
 use lambda_http::request::RequestContext;
 use lambda_http::{run, service_fn, Error, IntoResponse, Request, RequestExt, Response};
 use mysql::prelude::*;
 use mysql::{params, Opts, Pool, PooledConn};
 use serde::{Deserialize, Serialize};
 use handlebars::Handlebars;
+use once_cell::sync::OnceCell;
 use std::collections::HashMap;
 use std::env;
+use std::time::Instant;
 
 use handlebars::{ to_json };
 
+mod auth;
+mod csrf;
+mod error;
+mod metrics;
+mod password;
+use error::AppError;
+
+/// The MySQL connection pool, built once at cold start and reused across
+/// warm invocations of this Lambda.
+static POOL: OnceCell<Pool> = OnceCell::new();
+
+/// JWT configuration, built once at cold start alongside the pool.
+static AUTH_CONFIG: OnceCell<auth::Config> = OnceCell::new();
+
+fn pool() -> &'static Pool {
+    POOL.get().expect("pool is initialized in main before the runtime starts")
+}
+
+fn auth_config() -> &'static auth::Config {
+    AUTH_CONFIG
+        .get()
+        .expect("auth config is initialized in main before the runtime starts")
+}
+
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 struct Staff {
     staff_id: i32,
@@ -17,7 +43,10 @@ struct Staff {
     last_name: Option<String>,
     email: Option<String>,
     username: Option<String>,
+    #[serde(skip_serializing)]
     password: Option<String>,
+    #[serde(default, skip_serializing)]
+    csrf_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -27,23 +56,79 @@ struct Pagination {
     prev: i32
 }
 
-fn post_staff(event: Request, mut conn: PooledConn) -> Result<Response<String>, Error> {
-    let payload = match event.payload::<Staff>() {
-        Ok(Some(staff)) => staff,
-        _ => panic!("Can't create staff from input")
-    };
+#[derive(Deserialize)]
+struct Login {
+    username: String,
+    password: String,
+}
+
+/// Payload for `POST /staff`. Unlike `Staff`, `staff_id` isn't on the wire
+/// here — it's assigned by the database on insert.
+#[derive(Deserialize)]
+struct NewStaff {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    email: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    csrf_token: Option<String>,
+}
+
+/// Payload for `PUT`/`PATCH /staff`. `staff_id` is optional on the wire so
+/// that the `?staff_id=` query parameter can stand in for it.
+#[derive(Deserialize)]
+struct StaffUpdate {
+    #[serde(default)]
+    staff_id: Option<i32>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    email: Option<String>,
+    username: Option<String>,
+    #[serde(default)]
+    csrf_token: Option<String>,
+}
+
+#[tracing::instrument(skip(event, conn))]
+fn post_staff(event: Request, mut conn: PooledConn) -> Result<Response<String>, AppError> {
+    let wants_json = accepts_json(&event);
+
+    let cookie_token = event
+        .headers()
+        .get("Cookie")
+        .and_then(|value| value.to_str().ok())
+        .and_then(csrf::cookie_token)
+        .map(str::to_string);
 
-    let _ = conn.exec_drop(
-        r"INSERT INTO staff (first_name, last_name, email, username, password, store_id, address_id)
-        VALUES (:first_name, :last_name, :email, :username, :password, 1, 61)",
-        params! {
-            "first_name" => payload.first_name,
-            "last_name" => payload.last_name,
-            "email" => payload.email,
-            "username" => payload.username,
-            "password" => payload.password
+    let payload: NewStaff = parse_payload(&event)?;
+
+    if !wants_json {
+        match (&payload.csrf_token, &cookie_token) {
+            (Some(form_token), Some(cookie_token)) if csrf::tokens_match(form_token, cookie_token) => {}
+            _ => return Err(AppError::Forbidden("csrf token mismatch".to_string())),
         }
-    )?;
+    }
+
+    let password_hash = payload
+        .password
+        .as_deref()
+        .map(password::hash_password)
+        .transpose()
+        .map_err(|_| AppError::Internal("could not hash password".to_string()))?;
+
+    metrics::time_query(|| {
+        conn.exec_drop(
+            r"INSERT INTO staff (first_name, last_name, email, username, password, store_id, address_id)
+            VALUES (:first_name, :last_name, :email, :username, :password, 1, 61)",
+            params! {
+                "first_name" => payload.first_name,
+                "last_name" => payload.last_name,
+                "email" => payload.email,
+                "username" => payload.username,
+                "password" => password_hash
+            }
+        )
+    })?;
 
     event!(Level::INFO, "Create STAFF - Last generated key: {}",
         conn.last_insert_id());
@@ -52,56 +137,173 @@ fn post_staff(event: Request, mut conn: PooledConn) -> Result<Response<String>,
         .status(303)
         .header("Location", "/staff")
         .body(String::new())
-        .map_err(Box::new)?;
+        .expect("status and headers are always valid");
 
     Ok(resp)
 }
 
-fn get_single_staff(mut conn: PooledConn, staff_id: String) -> Result<Vec<Staff>, Error> {
+/// Deserialize the request body, turning a missing body or a parse
+/// failure into the same `AppError::BadRequest` every route already
+/// expects from a malformed submission.
+fn parse_payload<T: serde::de::DeserializeOwned>(event: &Request) -> Result<T, AppError> {
+    match event.payload::<T>() {
+        Ok(Some(payload)) => Ok(payload),
+        Ok(None) => Err(AppError::BadRequest("missing request body".to_string())),
+        Err(err) => Err(AppError::BadRequest(err.to_string())),
+    }
+}
+
+/// Whether the caller negotiated a JSON response (an API client) rather
+/// than the default form-flow redirect.
+fn accepts_json(event: &Request) -> bool {
+    event
+        .headers()
+        .get("Accept")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.contains("application/json"))
+        .unwrap_or(false)
+}
+
+fn redirect_or_no_content(wants_json: bool, location: &str) -> Response<String> {
+    let builder = if wants_json {
+        Response::builder().status(204)
+    } else {
+        Response::builder().status(303).header("Location", location)
+    };
+
+    builder
+        .body(String::new())
+        .expect("status and headers are always valid")
+}
+
+#[tracing::instrument(skip(event, conn))]
+fn put_staff(event: Request, mut conn: PooledConn) -> Result<Response<String>, AppError> {
+    let wants_json = accepts_json(&event);
+
+    let query_staff_id = event
+        .query_string_parameters()
+        .first("staff_id")
+        .and_then(|id| id.parse::<i32>().ok());
+
+    let cookie_token = event
+        .headers()
+        .get("Cookie")
+        .and_then(|value| value.to_str().ok())
+        .and_then(csrf::cookie_token)
+        .map(str::to_string);
+
+    let payload: StaffUpdate = parse_payload(&event)?;
+
+    if !wants_json {
+        match (&payload.csrf_token, &cookie_token) {
+            (Some(form_token), Some(cookie_token)) if csrf::tokens_match(form_token, cookie_token) => {}
+            _ => return Err(AppError::Forbidden("csrf token mismatch".to_string())),
+        }
+    }
+
+    let staff_id = query_staff_id
+        .or(payload.staff_id)
+        .ok_or_else(|| AppError::BadRequest("missing staff_id".to_string()))?;
+
+    metrics::time_query(|| {
+        conn.exec_drop(
+            r"UPDATE staff SET first_name=:first_name, last_name=:last_name, email=:email, username=:username
+            WHERE staff_id=:staff_id",
+            params! {
+                "first_name" => payload.first_name,
+                "last_name" => payload.last_name,
+                "email" => payload.email,
+                "username" => payload.username,
+                staff_id
+            }
+        )
+    })?;
+
+    if conn.affected_rows() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    event!(Level::INFO, "Update STAFF - id: {}", staff_id);
+
+    Ok(redirect_or_no_content(wants_json, "/staff"))
+}
+
+#[tracing::instrument(skip(event, conn))]
+fn delete_staff(event: Request, mut conn: PooledConn) -> Result<Response<String>, AppError> {
+    let wants_json = accepts_json(&event);
+
+    let staff_id = event
+        .query_string_parameters()
+        .first("staff_id")
+        .ok_or_else(|| AppError::BadRequest("missing staff_id".to_string()))?
+        .to_string();
+
+    event!(Level::INFO, "Delete STAFF - id: {}", staff_id);
+
+    metrics::time_query(|| {
+        conn.exec_drop(
+            "DELETE FROM staff WHERE staff_id=:staff_id",
+            params! { staff_id },
+        )
+    })?;
+
+    if conn.affected_rows() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(redirect_or_no_content(wants_json, "/staff"))
+}
+
+#[tracing::instrument(skip(conn))]
+fn get_single_staff(mut conn: PooledConn, staff_id: String) -> Result<Vec<Staff>, AppError> {
     event!(Level::INFO, "GET STAFF - by id: {}",
         staff_id);
 
-    let staff = conn
-        .exec_first(
-            "SELECT staff_id, first_name, last_name, email, username, password FROM staff WHERE staff_id=:staff_id",
+    let staff = metrics::time_query(|| {
+        conn.exec_first(
+            "SELECT staff_id, first_name, last_name, email, username FROM staff WHERE staff_id=:staff_id",
             params! {
                 staff_id
             }
-        ).map(|row|{
-            row.map(|(staff_id, first_name, last_name, email, username, password)| Staff {
-                staff_id,
-                first_name,
-                last_name,
-                email,
-                username,
-                password
-            })
-        })?
-        .unwrap();
+        )
+    })?
+        .map(|(staff_id, first_name, last_name, email, username)| Staff {
+            staff_id,
+            first_name,
+            last_name,
+            email,
+            username,
+            password: None,
+            csrf_token: None,
+        })
+        .ok_or(AppError::NotFound)?;
 
     Ok(vec![staff])
 }
 
-fn get_list_staff(mut conn: PooledConn, page_num: i32) -> Result<Vec<Staff>, Error> {
+#[tracing::instrument(skip(conn))]
+fn get_list_staff(mut conn: PooledConn, page_num: i32) -> Result<Vec<Staff>, AppError> {
     let offset = page_num * 10;
     event!(Level::INFO, "GET list of staff - at offset: {}",
         offset);
 
-    let staff = conn
-        .exec_map(
-            "SELECT staff_id, first_name, last_name, email, username, password FROM staff ORDER BY last_update desc LIMIT 10 OFFSET :offset",
+    let staff = metrics::time_query(|| {
+        conn.exec_map(
+            "SELECT staff_id, first_name, last_name, email, username FROM staff ORDER BY last_update desc LIMIT 10 OFFSET :offset",
             params! {
                 offset
             },
-            |(staff_id, first_name, last_name, email, username, password)| {
-                Staff { staff_id, first_name, last_name, email, username, password }
+            |(staff_id, first_name, last_name, email, username)| {
+                Staff { staff_id, first_name, last_name, email, username, password: None, csrf_token: None }
             },
-        )?;
+        )
+    })?;
 
     Ok(staff)
 }
 
-fn get_staff(event: Request, conn: PooledConn) -> Result<Response<String>, Error> {
+#[tracing::instrument(skip(event, conn))]
+fn get_staff(event: Request, conn: PooledConn) -> Result<Response<String>, AppError> {
     let params = event.query_string_parameters();
 
     let page_num = match params.first("page") {
@@ -130,22 +332,67 @@ fn get_staff(event: Request, conn: PooledConn) -> Result<Response<String>, Error
     // It will be serialized to the right response event automatically by the runtime
     // let body = serde_json::to_string(&staff).unwrap();
 
+    let csrf_token = showform.then(csrf::generate_token);
+
     let mut data = HashMap::new();
     data.insert("staff", to_json(&staff));
     data.insert("pagination", to_json(&pagination));
     data.insert("showform", to_json(&showform));
+    if let Some(token) = &csrf_token {
+        data.insert("csrf_token", to_json(token));
+    }
 
     let mut handlebars = Handlebars::new();
     handlebars
         .register_template_string("staff", include_str!("../templates/staff.hbs"))
-        .unwrap();
+        .expect("staff.hbs is a valid compile-time template");
 
-    let body = handlebars.render("staff", &data).unwrap();
-    let resp = Response::builder()
+    let body = handlebars.render("staff", &data)?;
+    let mut builder = Response::builder()
         .status(200)
-        .header("content-type", "text/html")
+        .header("content-type", "text/html");
+
+    if let Some(token) = &csrf_token {
+        builder = builder.header(
+            "Set-Cookie",
+            format!("{}={}; Path=/staff; SameSite=Strict", csrf::COOKIE_NAME, token),
+        );
+    }
+
+    let resp = builder
         .body(body)
-        .map_err(Box::new)?;
+        .expect("status and headers are always valid");
+
+    Ok(resp)
+}
+
+#[tracing::instrument(skip(event, conn))]
+fn login_staff(event: Request, mut conn: PooledConn) -> Result<Response<String>, AppError> {
+    let payload: Login = parse_payload(&event)?;
+
+    let (staff_id, password_hash): (i32, Option<String>) = metrics::time_query(|| {
+        conn.exec_first(
+            "SELECT staff_id, password FROM staff WHERE username=:username",
+            params! { "username" => payload.username },
+        )
+    })?
+        .ok_or(AppError::Unauthorized)?;
+    let verified = password_hash
+        .as_deref()
+        .map(|phc| password::verify_password(&payload.password, phc))
+        .unwrap_or(false);
+
+    if !verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    let token = auth::issue_token(staff_id, auth_config())?;
+
+    let resp = Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(format!(r#"{{"token":"{}"}}"#, token))
+        .expect("status and headers are always valid");
 
     Ok(resp)
 }
@@ -155,13 +402,29 @@ async fn router(
     path: &str,
     event: Request,
     pool: PooledConn,
-) -> Result<impl IntoResponse, Error> {
+) -> Result<Response<String>, AppError> {
     let method_path = (method, path);
     match method_path {
+        ("POST", "/login") => login_staff(event, pool),
+
+        ("POST", "/staff") => {
+            auth::authorize(&event, auth_config())?;
+            post_staff(event, pool)
+        }
+
+        ("PUT", "/staff") | ("PATCH", "/staff") => {
+            auth::authorize(&event, auth_config())?;
+            put_staff(event, pool)
+        }
+
+        ("DELETE", "/staff") => {
+            auth::authorize(&event, auth_config())?;
+            delete_staff(event, pool)
+        }
+
         ("GET", "/staff") => get_staff(event, pool),
-        ("POST", "/staff") => post_staff(event, pool),
 
-        _ => panic!("Failed to match method and path"),
+        _ => Err(AppError::NotFound),
     }
 }
 
@@ -173,29 +436,73 @@ async fn function_handler(event: Request) -> Result<impl IntoResponse, Error> {
     let path = event.raw_http_path();
 
     let ctx = event.request_context();
-    let method = match ctx {
+    let method = match &ctx {
         RequestContext::ApiGatewayV2(context) => context.http.method.to_string(),
         _ => "UNKNOWN".to_string(),
     };
+    let request_id = match &ctx {
+        RequestContext::ApiGatewayV2(context) => context.request_id.clone().unwrap_or_default(),
+        _ => String::new(),
+    };
+    let trace_id = event
+        .headers()
+        .get("x-amzn-trace-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %method,
+        path = %path,
+        trace_id = %trace_id,
+    );
+
+    async move {
+        metrics::reset();
+        let handler_start = Instant::now();
+
+        event!(Level::INFO, "Received {} request on {}", method, path);
+
+        let result = match metrics::time_db_connect(|| pool().try_get_conn(1000)) {
+            Ok(conn) => router(&method, &path, event, conn).await,
+            Err(err) => Err(AppError::Database(err)),
+        };
+
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(err) => {
+                event!(Level::ERROR, "request failed: {}", err);
+                err.into_response()
+            }
+        };
 
-    event!(Level::INFO, "Received {} request on {}", method, path);
-
-    let url: String = env::var("MYSQL_URL").unwrap();
-    let pool = Pool::new(Opts::from_url(&url)?)?;
+        metrics::emit(&method, &path, resp.status().as_u16(), handler_start.elapsed());
 
-    match pool.try_get_conn(1000) {
-        Ok(conn) => router(&method, &path, event, conn).await,
-        _ => panic!("Failed to connect to backend"),
+        Ok(resp)
     }
+    .instrument(span)
+    .await
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing_subscriber::fmt()
+        .json()
         .with_max_level(tracing::Level::INFO)
         // disabling time is handy because CloudWatch will add the ingestion time.
         .without_time()
         .init();
 
+    let url: String = env::var("MYSQL_URL").map_err(|_| AppError::MissingConfig("MYSQL_URL"))?;
+    let pool = Pool::new(Opts::from_url(&url)?)?;
+    POOL.set(pool).expect("main only initializes the pool once");
+
+    let config = auth::Config::from_env()?;
+    AUTH_CONFIG
+        .set(config)
+        .unwrap_or_else(|_| panic!("main only initializes the auth config once"));
+
     run(service_fn(function_handler)).await
 }
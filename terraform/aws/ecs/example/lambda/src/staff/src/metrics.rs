@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Per-invocation timing totals, accumulated as the handler runs and
+/// flushed as a single EMF line at the end of `function_handler`.
+#[derive(Default)]
+struct Invocation {
+    db_connect: Duration,
+    query: Duration,
+}
+
+thread_local! {
+    static INVOCATION: RefCell<Invocation> = RefCell::new(Invocation::default());
+}
+
+/// Reset the per-invocation accumulator. Call once at the start of
+/// `function_handler`, since Lambda may reuse this thread across warm
+/// invocations.
+pub fn reset() {
+    INVOCATION.with(|m| *m.borrow_mut() = Invocation::default());
+}
+
+/// Time a DB connection acquisition and add it to this invocation's total.
+pub fn time_db_connect<T>(f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    INVOCATION.with(|m| m.borrow_mut().db_connect += start.elapsed());
+    result
+}
+
+/// Time a query execution and add it to this invocation's total.
+pub fn time_query<T>(f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    INVOCATION.with(|m| m.borrow_mut().query += start.elapsed());
+    result
+}
+
+/// Emit this invocation's metrics as a CloudWatch Embedded Metric Format
+/// (EMF) JSON line to stdout, tagged by route and status so dashboards can
+/// break down errors by endpoint. CloudWatch Logs auto-ingests these as
+/// metrics without any extra API calls.
+pub fn emit(method: &str, path: &str, status: u16, handler_duration: Duration) {
+    let (db_connect_ms, query_ms) = INVOCATION.with(|m| {
+        let m = m.borrow();
+        (as_millis(m.db_connect), as_millis(m.query))
+    });
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    let emf = serde_json::json!({
+        "_aws": {
+            "Timestamp": timestamp,
+            "CloudWatchMetrics": [{
+                "Namespace": "StaffLambda",
+                "Dimensions": [["Route", "Status"]],
+                "Metrics": [
+                    { "Name": "RequestCount", "Unit": "Count" },
+                    { "Name": "HandlerDuration", "Unit": "Milliseconds" },
+                    { "Name": "DbConnectDuration", "Unit": "Milliseconds" },
+                    { "Name": "QueryDuration", "Unit": "Milliseconds" }
+                ]
+            }]
+        },
+        "Route": format!("{} {}", method, path),
+        "Status": status.to_string(),
+        "RequestCount": 1,
+        "HandlerDuration": as_millis(handler_duration),
+        "DbConnectDuration": db_connect_ms,
+        "QueryDuration": query_ms,
+    });
+
+    println!("{}", emf);
+}
+
+fn as_millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}